@@ -15,7 +15,12 @@ fn main() {
     infile.read_to_end(&mut infile_contents).unwrap();
 
     let mut outfile_contents = Vec::new();
-    ogg2caf::convert(Cursor::new(infile_contents), &mut outfile_contents).unwrap();
+    ogg2caf::convert(
+        Cursor::new(infile_contents),
+        &mut outfile_contents,
+        ogg2caf::GainMode::TrackGain,
+    )
+    .unwrap();
 
     println!("writing file: {}", outfile_path);
     let mut outfile = File::create_new(outfile_path).unwrap();
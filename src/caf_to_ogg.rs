@@ -0,0 +1,210 @@
+use crate::opus::{
+    channel_count_for_layout_tag, decode_channel_mapping_table, packet_frame_count,
+    ChannelMappingTable, OpusHead, OpusTags, CHANNEL_MAPPING_INFO_KEY,
+};
+use crate::util::map_caf_info_to_comments;
+use anyhow::{anyhow, Error};
+use caf::{reading::CafPacketReader, FormatType};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use std::io::{Cursor, Read, Seek, Write};
+
+/// The Ogg logical stream serial number used for the single Opus stream we write. Since
+/// the output is a fresh file with one stream, any fixed value works.
+const STREAM_SERIAL: u32 = 1;
+
+/// Reads a CAF file produced by [`crate::convert`] (or by Apple's own Opus-in-CAF tooling)
+/// and repackages it as an Ogg Opus file, reconstructing the `OpusHead`/`OpusTags` header
+/// packets from the CAF audio description, priming frames, and Information chunk.
+pub fn convert_reverse<R: Read + Seek, W: Write>(rdr: R, wtr: W) -> Result<(), Error> {
+    let mut caf_reader = CafPacketReader::new(rdr)?;
+    let audio_description = caf_reader.audio_description().clone();
+
+    let is_opus = matches!(
+        audio_description.format_id,
+        FormatType::Other(id) if id == u32::from_be_bytes(*b"opus")
+    );
+    if !is_opus {
+        return Err(anyhow!(
+            "only CAF files containing an Opus stream can be converted back to Ogg"
+        ));
+    }
+    let channel_count = audio_description.channels_per_frame as u8;
+
+    let preskip = caf_reader.priming_frames().max(0) as u16;
+
+    let info = caf_reader.info_chunk().unwrap_or_default();
+    let (vendor_string, user_comments) = map_caf_info_to_comments(&info);
+    let vendor_string = if vendor_string.is_empty() {
+        "ogg2caf".to_string()
+    } else {
+        vendor_string
+    };
+    let magic_cookie = caf_reader.magic_cookie();
+    let output_gain = parse_output_gain(magic_cookie.as_deref());
+
+    // a channel layout chunk only carries a derived speaker-layout tag, not the original
+    // stream/coupled counts a multistream Opus packet is actually demultiplexed with; the
+    // forward conversion stashes those in a private info entry, since fabricating them
+    // (e.g. one independent mono stream per channel) would make a real decoder misparse
+    // the self-delimited framing of genuine coupled streams and produce garbage audio. A
+    // CAF layout chunk is only emitted for channel counts with a well-known tag (1/2/6/8),
+    // so its absence doesn't mean the stream wasn't multichannel — trust the recovered
+    // table whenever it's there, and return an error when a layout chunk implies a mapping
+    // we have no way to recover (e.g. the file wasn't produced by this tool).
+    let has_layout_tag = caf_reader
+        .channel_layout_tag()
+        .and_then(channel_count_for_layout_tag)
+        .is_some();
+    let channel_mapping = info
+        .iter()
+        .find(|(key, _)| key == CHANNEL_MAPPING_INFO_KEY)
+        .and_then(|(_, value)| decode_channel_mapping_table(value));
+    let (channel_mapping_family, channel_mapping_table) =
+        resolve_channel_mapping(has_layout_tag, channel_mapping)?;
+
+    let opus_head = OpusHead {
+        channel_count,
+        preskip,
+        input_sample_rate: audio_description.sample_rate as u32,
+        output_gain,
+        channel_mapping_family,
+        channel_mapping_table,
+    };
+    let opus_tags = OpusTags::new(vendor_string, user_comments);
+
+    let mut id_header_packet = Vec::new();
+    opus_head.write(&mut id_header_packet)?;
+    let mut comment_header_packet = Vec::new();
+    opus_tags.write(&mut comment_header_packet)?;
+
+    let mut ogg_writer = PacketWriter::new(wtr);
+    ogg_writer.write_packet(id_header_packet, STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+    ogg_writer.write_packet(
+        comment_header_packet,
+        STREAM_SERIAL,
+        PacketWriteEndInfo::EndPage,
+        0,
+    )?;
+
+    let mut granule_position = preskip as u64;
+    let mut packets = caf_reader.packets().peekable();
+    while let Some(packet_data) = packets.next().transpose()? {
+        granule_position += packet_frame_count(&packet_data)? as u64;
+        let end_info = if packets.peek().is_some() {
+            PacketWriteEndInfo::NormalPacket
+        } else {
+            PacketWriteEndInfo::EndStream
+        };
+        ogg_writer.write_packet(packet_data, STREAM_SERIAL, end_info, granule_position)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the combined gain `opus::convert` folds into the magic cookie's `OpusHead.
+/// output_gain` (see the forward conversion's gain-mode handling), falling back to `0` if
+/// there's no magic cookie or it doesn't parse as an `OpusHead`.
+fn parse_output_gain(magic_cookie: Option<&[u8]>) -> i16 {
+    magic_cookie
+        .and_then(|bytes| OpusHead::read(Cursor::new(bytes)).ok())
+        .map(|head| head.output_gain)
+        .unwrap_or(0)
+}
+
+/// Decides the `OpusHead` channel mapping family and table to reconstruct. Trusts a
+/// recovered private channel mapping entry whenever one is present, regardless of whether a
+/// CAF Channel Layout chunk was written (the layout chunk only exists for the handful of
+/// channel counts with a well-known tag, so its absence doesn't mean there's no mapping to
+/// recover). Returns an error rather than fabricating stream/coupled counts when a layout
+/// chunk implies a mapping but nothing was recoverable (see [`convert_reverse`]).
+fn resolve_channel_mapping(
+    has_layout_tag: bool,
+    channel_mapping: Option<(u8, ChannelMappingTable)>,
+) -> Result<(u8, Option<ChannelMappingTable>), Error> {
+    match channel_mapping {
+        Some((family, table)) => Ok((family, Some(table))),
+        None if has_layout_tag => Err(anyhow!(
+            "CAF file has a multichannel layout but no recoverable Opus channel mapping \
+             table (it wasn't produced by this tool's forward conversion); refusing to \
+             fabricate stream/coupled counts, which would corrupt multistream playback"
+        )),
+        None => Ok((0, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_output_gain, resolve_channel_mapping};
+    use crate::opus::{ChannelMappingTable, OpusHead};
+
+    fn magic_cookie_with_gain(output_gain: i16) -> Vec<u8> {
+        let opus_head = OpusHead {
+            channel_count: 2,
+            preskip: 0,
+            input_sample_rate: 48000,
+            output_gain,
+            channel_mapping_family: 0,
+            channel_mapping_table: None,
+        };
+        let mut bytes = Vec::new();
+        opus_head.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    pub fn parse_output_gain_reads_magic_cookie() {
+        let cookie = magic_cookie_with_gain(-512);
+        assert_eq!(parse_output_gain(Some(&cookie)), -512);
+    }
+
+    #[test]
+    pub fn parse_output_gain_defaults_to_zero_when_absent() {
+        assert_eq!(parse_output_gain(None), 0);
+    }
+
+    #[test]
+    pub fn parse_output_gain_defaults_to_zero_when_not_an_opus_head() {
+        assert_eq!(parse_output_gain(Some(b"not an opus head")), 0);
+    }
+
+    #[test]
+    pub fn resolve_channel_mapping_without_layout_tag_has_no_mapping() {
+        let (family, table) = resolve_channel_mapping(false, None).unwrap();
+        assert_eq!(family, 0);
+        assert!(table.is_none());
+    }
+
+    #[test]
+    pub fn resolve_channel_mapping_uses_recovered_table() {
+        let recovered = ChannelMappingTable {
+            stream_count: 4,
+            coupled_count: 2,
+            channel_mapping: vec![0, 1, 2, 3, 4, 5],
+        };
+        let (family, table) = resolve_channel_mapping(true, Some((1, recovered))).unwrap();
+        assert_eq!(family, 1);
+        let table = table.unwrap();
+        assert_eq!(table.stream_count, 4);
+        assert_eq!(table.coupled_count, 2);
+    }
+
+    #[test]
+    pub fn resolve_channel_mapping_uses_recovered_table_without_layout_tag() {
+        // e.g. a genuine quad (4-channel) family-1 stream: there's no well-known CAF layout
+        // tag for 4 channels, so no Channel Layout chunk is written, but the private info
+        // entry is still there and must not be discarded.
+        let recovered = ChannelMappingTable {
+            stream_count: 2,
+            coupled_count: 2,
+            channel_mapping: vec![0, 1, 2, 3],
+        };
+        let (family, table) = resolve_channel_mapping(false, Some((1, recovered))).unwrap();
+        assert_eq!(family, 1);
+        assert_eq!(table.unwrap().channel_mapping, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    pub fn resolve_channel_mapping_rejects_layout_tag_without_recovered_table() {
+        assert!(resolve_channel_mapping(true, None).is_err());
+    }
+}
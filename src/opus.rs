@@ -0,0 +1,592 @@
+use crate::util::{map_comments_to_caf_info, read_length_prefixed_bytes};
+use anyhow::{anyhow, Error};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use caf::{chunks::AudioDescription, writing::PacketWriter, FormatType};
+use ogg::{Packet, PacketReader};
+use std::io::{Cursor, Read, Seek, Write};
+
+pub(crate) fn convert<R: Read + Seek, W: Write>(
+    id_header_packet: Packet,
+    mut packet_reader: PacketReader<R>,
+    wtr: W,
+    gain_mode: GainMode,
+) -> Result<(), Error> {
+    let comment_header_packet = packet_reader
+        .read_packet()?
+        .ok_or(anyhow!("missing comment header packet"))?;
+
+    // parse opus headers into caf audio description
+    let opus_head = OpusHead::read(Cursor::new(id_header_packet.data))?;
+    let opus_tags = OpusTags::read(Cursor::new(comment_header_packet.data))?;
+    let sample_rate = if opus_head.input_sample_rate == 0 {
+        48000.0
+    } else {
+        opus_head.input_sample_rate as f64
+    };
+    let audio_description = AudioDescription {
+        sample_rate,
+        format_id: FormatType::Other(u32::from_be_bytes(*b"opus")),
+        format_flags: 0,
+        bytes_per_packet: 0,
+        frames_per_packet: 0, // opus packets are variable-length, so this is a VBR stream
+        channels_per_frame: opus_head.channel_count as u32,
+        bits_per_channel: 0,
+    };
+
+    // write
+    let mut packet_writer = PacketWriter::new(wtr, &audio_description)?;
+
+    // carry the Vorbis comments from the OpusTags header into a CAF Information chunk
+    let mut info = opus_tags.to_caf_info();
+    if let Some(table) = &opus_head.channel_mapping_table {
+        // the Channel Layout chunk below only carries a derived speaker-layout tag, which
+        // isn't enough to reconstruct how the original packets are multiplexed; stash the
+        // exact mapping in a private info entry so `caf_to_ogg::convert_reverse` can restore
+        // the original family, stream/coupled counts and mapping instead of guessing at them
+        info.push((
+            CHANNEL_MAPPING_INFO_KEY.to_string(),
+            encode_channel_mapping_table(opus_head.channel_mapping_family, table),
+        ));
+    }
+    packet_writer.write_info_chunk(&info)?;
+
+    // the magic cookie is the raw OpusHead structure, which is what CoreAudio's Opus
+    // decoder actually reads `output_gain` from and applies at render time (unlike the
+    // Information chunk, which is just display metadata); fold the combined gain in there
+    // rather than the header's original output_gain, so normalized loudness takes effect
+    let mut magic_cookie_head = opus_head.clone();
+    if let Some(gain_db) = effective_gain_db(&opus_head, &opus_tags, gain_mode) {
+        magic_cookie_head.output_gain = (gain_db * 256.0).round() as i16;
+    }
+    let mut magic_cookie = Vec::new();
+    magic_cookie_head.write(&mut magic_cookie)?;
+    packet_writer.write_magic_cookie_chunk(&magic_cookie)?;
+
+    // translate a family-1 (surround) channel mapping into a CAF Channel Layout chunk
+    if opus_head.channel_mapping_family == 1 {
+        if let Some(tag) = family_1_channel_layout_tag(opus_head.channel_count) {
+            packet_writer.write_channel_layout_chunk(tag)?;
+        }
+    }
+
+    // preskip
+    packet_writer.set_priming_frames(opus_head.preskip as i32);
+
+    // read audio data packets from ogg and add them to caf
+    while let Some(packet) = packet_reader.read_packet()? {
+        let frame_count = packet_frame_count(&packet.data)?;
+        packet_writer.add_packet(&packet.data, Some(frame_count))?;
+    }
+    packet_writer.write_audio_data()?;
+
+    Ok(())
+}
+
+/// Selects which R128 loudness comment, if any, is combined with the Opus header's
+/// `output_gain` to produce the gain value written into the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GainMode {
+    /// Use only the header `output_gain`; ignore R128 comments.
+    #[default]
+    None,
+    /// Combine `output_gain` with the `R128_TRACK_GAIN` comment, if present.
+    TrackGain,
+    /// Combine `output_gain` with the `R128_ALBUM_GAIN` comment, if present.
+    AlbumGain,
+}
+
+/// The Opus multistream mapping table (RFC 7845 section 5.1.1): how the coded streams
+/// in a packet are decoded and coupled into the output channels.
+#[derive(Clone)]
+pub struct ChannelMappingTable {
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    /// One entry per output channel, giving the decoded channel index it's taken from.
+    pub channel_mapping: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct OpusHead {
+    pub channel_count: u8,
+    pub preskip: u16,
+    pub input_sample_rate: u32,
+    pub output_gain: i16,
+    pub channel_mapping_family: u8,
+    pub channel_mapping_table: Option<ChannelMappingTable>,
+}
+
+impl OpusHead {
+    pub fn read<T: Read>(mut rdr: T) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        rdr.read_exact(&mut magic)?;
+        if magic != *b"OpusHead" {
+            return Err(anyhow!("missing magic signature"));
+        }
+
+        let version = rdr.read_u8()?;
+        if version != 0x01 {
+            return Err(anyhow!("invalid version"));
+        }
+
+        let channel_count = rdr.read_u8()?;
+        let preskip = rdr.read_u16::<LE>()?;
+        let input_sample_rate = rdr.read_u32::<LE>()?;
+        let output_gain = rdr.read_i16::<LE>()?;
+
+        let channel_mapping_family = rdr.read_u8()?;
+        let channel_mapping_table = if channel_mapping_family != 0 {
+            let stream_count = rdr.read_u8()?;
+            let coupled_count = rdr.read_u8()?;
+
+            let mut channel_mapping = vec![0; channel_count as usize];
+            rdr.read_exact(&mut channel_mapping)?;
+
+            Some(ChannelMappingTable {
+                stream_count,
+                coupled_count,
+                channel_mapping,
+            })
+        } else {
+            None
+        };
+
+        Ok(OpusHead {
+            channel_count,
+            preskip,
+            input_sample_rate,
+            output_gain,
+            channel_mapping_family,
+            channel_mapping_table,
+        })
+    }
+
+    /// Serializes the header back into an `OpusHead` packet, the inverse of [`OpusHead::read`].
+    pub fn write<W: Write>(&self, mut wtr: W) -> Result<(), Error> {
+        wtr.write_all(b"OpusHead")?;
+        wtr.write_u8(0x01)?; // version
+        wtr.write_u8(self.channel_count)?;
+        wtr.write_u16::<LE>(self.preskip)?;
+        wtr.write_u32::<LE>(self.input_sample_rate)?;
+        wtr.write_i16::<LE>(self.output_gain)?;
+        wtr.write_u8(self.channel_mapping_family)?;
+
+        if let Some(mapping) = &self.channel_mapping_table {
+            wtr.write_u8(mapping.stream_count)?;
+            wtr.write_u8(mapping.coupled_count)?;
+            wtr.write_all(&mapping.channel_mapping)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct OpusTags {
+    vendor_string: String,
+    user_comments: Vec<String>,
+}
+
+impl OpusTags {
+    /// Builds an `OpusTags` from an already-parsed vendor string and `KEY=value` comments.
+    pub fn new(vendor_string: String, user_comments: Vec<String>) -> Self {
+        Self {
+            vendor_string,
+            user_comments,
+        }
+    }
+
+    pub fn read<T: Read>(mut rdr: T) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        rdr.read_exact(&mut magic)?;
+        if magic != *b"OpusTags" {
+            return Err(anyhow!("missing magic signature"));
+        }
+
+        let vendor_string_len = rdr.read_u32::<LE>()?;
+        let vendor_string_bytes = read_length_prefixed_bytes(&mut rdr, vendor_string_len)?;
+        let vendor_string = String::from_utf8(vendor_string_bytes)?;
+
+        let user_comments_count = rdr.read_u32::<LE>()?;
+        let mut user_comments = Vec::new();
+        for _ in 0..user_comments_count {
+            let user_comment_len = rdr.read_u32::<LE>()?;
+            let user_comment_bytes = read_length_prefixed_bytes(&mut rdr, user_comment_len)?;
+            let user_comment = String::from_utf8(user_comment_bytes)?;
+            user_comments.push(user_comment);
+        }
+
+        Ok(Self {
+            vendor_string,
+            user_comments,
+        })
+    }
+
+    /// Maps the vendor string and the well-known Vorbis comment keys to their CAF
+    /// Information chunk equivalents, in order. Unrecognized comments are dropped.
+    pub fn to_caf_info(&self) -> Vec<(String, String)> {
+        let comments = self
+            .user_comments
+            .iter()
+            .filter_map(|comment| comment.split_once('='));
+        map_comments_to_caf_info(&self.vendor_string, comments)
+    }
+
+    /// Finds a `KEY=value` comment by key (case-insensitive) and returns its value.
+    fn find_comment(&self, key: &str) -> Option<&str> {
+        self.user_comments.iter().find_map(|comment| {
+            let (comment_key, value) = comment.split_once('=')?;
+            comment_key.eq_ignore_ascii_case(key).then_some(value)
+        })
+    }
+
+    /// Serializes the tags back into an `OpusTags` packet, the inverse of [`OpusTags::read`].
+    pub fn write<W: Write>(&self, mut wtr: W) -> Result<(), Error> {
+        wtr.write_all(b"OpusTags")?;
+
+        wtr.write_u32::<LE>(self.vendor_string.len() as u32)?;
+        wtr.write_all(self.vendor_string.as_bytes())?;
+
+        wtr.write_u32::<LE>(self.user_comments.len() as u32)?;
+        for comment in &self.user_comments {
+            wtr.write_u32::<LE>(comment.len() as u32)?;
+            wtr.write_all(comment.as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The CAF Information chunk key under which the forward conversion stashes a channel
+/// mapping table so it can be recovered exactly by [`crate::caf_to_ogg::convert_reverse`];
+/// see [`encode_channel_mapping_table`]. Namespaced like a private comment key since it
+/// isn't part of the CAF spec's well-known Information chunk keys.
+pub(crate) const CHANNEL_MAPPING_INFO_KEY: &str = "com.github.hazelmeow.ogg2caf.channel-mapping";
+
+/// Serializes a channel mapping family and table as `family,stream_count,coupled_count,
+/// mapping...`, the inverse of [`decode_channel_mapping_table`].
+pub(crate) fn encode_channel_mapping_table(family: u8, table: &ChannelMappingTable) -> String {
+    let mut fields = vec![family, table.stream_count, table.coupled_count];
+    fields.extend_from_slice(&table.channel_mapping);
+    fields
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses a value produced by [`encode_channel_mapping_table`] back into the channel
+/// mapping family and table, or `None` if it's malformed.
+pub(crate) fn decode_channel_mapping_table(value: &str) -> Option<(u8, ChannelMappingTable)> {
+    let fields = value
+        .split(',')
+        .map(|field| field.parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let [family, stream_count, coupled_count, channel_mapping @ ..] = fields.as_slice() else {
+        return None;
+    };
+    Some((
+        *family,
+        ChannelMappingTable {
+            stream_count: *stream_count,
+            coupled_count: *coupled_count,
+            channel_mapping: channel_mapping.to_vec(),
+        },
+    ))
+}
+
+/// Returns the CAF `AudioChannelLayoutTag` that best matches channel mapping family 1
+/// (the RFC 7845 section 5.1.1.2 "Vorbis channel order") for a given channel count, or
+/// `None` if there's no well-known tag for that count (the stream still plays back fine
+/// as a bare group of channels, just without an explicit speaker layout).
+pub(crate) fn family_1_channel_layout_tag(channel_count: u8) -> Option<u32> {
+    const fn tag(family: u32, channels: u32) -> u32 {
+        (family << 16) | channels
+    }
+    match channel_count {
+        1 => Some(tag(100, 1)), // kAudioChannelLayoutTag_Mono
+        2 => Some(tag(101, 2)), // kAudioChannelLayoutTag_Stereo
+        // the "_A" variants (121/126) order channels L R C LFE Ls Rs, which doesn't match
+        // RFC 7845's L C R Ls Rs LFE order; the "_C" variants below are the ones that do
+        6 => Some(tag(123, 6)), // kAudioChannelLayoutTag_MPEG_5_1_C
+        8 => Some(tag(128, 8)), // kAudioChannelLayoutTag_MPEG_7_1_C
+        _ => None,
+    }
+}
+
+/// Reverses [`family_1_channel_layout_tag`]: given a CAF `AudioChannelLayoutTag`, returns
+/// the channel count it implies, if it's one of the tags we know how to emit.
+pub(crate) fn channel_count_for_layout_tag(tag: u32) -> Option<u8> {
+    [1, 2, 6, 8]
+        .into_iter()
+        .find(|&count| family_1_channel_layout_tag(count) == Some(tag))
+}
+
+/// Returns the number of 48kHz samples encoded in a single Opus packet, derived from the
+/// TOC (table-of-contents) byte per RFC 6716 section 3.1.
+pub(crate) fn packet_frame_count(packet: &[u8]) -> Result<u32, Error> {
+    let toc = *packet.first().ok_or(anyhow!("empty opus packet"))?;
+    let config = toc >> 3;
+    let code = toc & 0x3;
+
+    let samples_per_frame: u32 = match config {
+        // SILK-only, NB/MB/WB: 10/20/40/60ms cycling every 4 configs
+        0..=11 => [10, 20, 40, 60][(config % 4) as usize] * 48,
+        // Hybrid, SWB/FB: 10/20ms cycling every 2 configs
+        12..=15 => [10, 20][(config % 2) as usize] * 48,
+        // CELT-only, NB/WB/SWB/FB: 2.5/5/10/20ms cycling every 4 configs
+        16..=31 => [120, 240, 480, 960][(config % 4) as usize],
+        _ => unreachable!("config is a 5-bit value"),
+    };
+
+    let frame_count: u32 = match code {
+        0 => 1,
+        1 | 2 => 2,
+        // code 3: an extra byte gives the frame count in its low 6 bits
+        3 => {
+            let byte = *packet
+                .get(1)
+                .ok_or(anyhow!("truncated opus packet: missing frame count byte"))?;
+            (byte & 0x3f) as u32
+        }
+        _ => unreachable!("code is a 2-bit value"),
+    };
+
+    Ok(frame_count * samples_per_frame)
+}
+
+/// Combines the Opus header's `output_gain` with the R128 comment selected by `gain_mode`
+/// (if present) into a single gain value in dB, or `None` if there's nothing to apply.
+pub(crate) fn effective_gain_db(
+    opus_head: &OpusHead,
+    opus_tags: &OpusTags,
+    gain_mode: GainMode,
+) -> Option<f64> {
+    let r128_key = match gain_mode {
+        GainMode::None => None,
+        GainMode::TrackGain => Some("R128_TRACK_GAIN"),
+        GainMode::AlbumGain => Some("R128_ALBUM_GAIN"),
+    };
+
+    let header_gain_db = opus_head.output_gain as f64 / 256.0;
+    let r128_gain_db = r128_key
+        .and_then(|key| opus_tags.find_comment(key))
+        .and_then(|value| value.parse::<i32>().ok())
+        .map(|q7_8| q7_8 as f64 / 256.0);
+
+    match (header_gain_db, r128_gain_db) {
+        (0.0, None) => None,
+        (header, r128) => Some(header + r128.unwrap_or(0.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_channel_mapping_table, effective_gain_db, encode_channel_mapping_table,
+        family_1_channel_layout_tag, packet_frame_count, ChannelMappingTable, GainMode, OpusHead,
+        OpusTags,
+    };
+    use std::io::{Cursor, ErrorKind, Read};
+
+    #[test]
+    pub fn read_opus_head() {
+        let mut rdr = Cursor::new(&[
+            0x4f, 0x70, 0x75, 0x73, 0x48, 0x65, 0x61, 0x64, 0x01, 0x02, 0x38, 0x01, 0x80, 0xbb,
+            0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        let opus_head = OpusHead::read(&mut rdr).unwrap();
+        assert_eq!(opus_head.channel_count, 2);
+        assert_eq!(opus_head.preskip, 312);
+        assert_eq!(opus_head.input_sample_rate, 48000);
+        assert_eq!(opus_head.output_gain, 0);
+        assert_eq!(opus_head.channel_mapping_family, 0);
+
+        let read_err = rdr.read_exact(&mut [0]).expect_err("should be EOF");
+        assert_eq!(read_err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    pub fn read_opus_head_with_channel_mapping_table() {
+        let mut rdr = Cursor::new(&[
+            0x4f, 0x70, 0x75, 0x73, 0x48, 0x65, 0x61, 0x64, // "OpusHead"
+            0x01, // version
+            0x06, // channel_count = 6
+            0x00, 0x00, // preskip = 0
+            0x80, 0xbb, 0x00, 0x00, // input_sample_rate = 48000
+            0x00, 0x00, // output_gain = 0
+            0x01, // channel_mapping_family = 1
+            0x04, // stream_count = 4
+            0x02, // coupled_count = 2
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, // channel_mapping
+        ]);
+        let opus_head = OpusHead::read(&mut rdr).unwrap();
+        assert_eq!(opus_head.channel_count, 6);
+        assert_eq!(opus_head.channel_mapping_family, 1);
+        let mapping = opus_head.channel_mapping_table.unwrap();
+        assert_eq!(mapping.stream_count, 4);
+        assert_eq!(mapping.coupled_count, 2);
+        assert_eq!(mapping.channel_mapping, vec![0, 1, 2, 3, 4, 5]);
+
+        let read_err = rdr.read_exact(&mut [0]).expect_err("should be EOF");
+        assert_eq!(read_err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    pub fn opus_head_write_round_trips_output_gain() {
+        let opus_head = OpusHead {
+            channel_count: 2,
+            preskip: 312,
+            input_sample_rate: 48000,
+            output_gain: -512,
+            channel_mapping_family: 0,
+            channel_mapping_table: None,
+        };
+
+        let mut bytes = Vec::new();
+        opus_head.write(&mut bytes).unwrap();
+        let round_tripped = OpusHead::read(Cursor::new(bytes)).unwrap();
+        assert_eq!(round_tripped.output_gain, -512);
+        assert_eq!(round_tripped.channel_count, 2);
+        assert_eq!(round_tripped.preskip, 312);
+    }
+
+    #[test]
+    pub fn family_1_channel_layout_tag_known_counts() {
+        assert_eq!(family_1_channel_layout_tag(1), Some((100 << 16) | 1));
+        assert_eq!(family_1_channel_layout_tag(2), Some((101 << 16) | 2));
+        assert_eq!(family_1_channel_layout_tag(6), Some((123 << 16) | 6));
+        assert_eq!(family_1_channel_layout_tag(8), Some((128 << 16) | 8));
+    }
+
+    #[test]
+    pub fn family_1_channel_layout_tag_unknown_count() {
+        assert_eq!(family_1_channel_layout_tag(3), None);
+    }
+
+    #[test]
+    pub fn read_opus_tags() {
+        let mut rdr = Cursor::new(&[
+            0x4f, 0x70, 0x75, 0x73, 0x54, 0x61, 0x67, 0x73, 0x0d, 0x00, 0x00, 0x00, 0x4c, 0x61,
+            0x76, 0x66, 0x35, 0x38, 0x2e, 0x32, 0x39, 0x2e, 0x31, 0x30, 0x30, 0x01, 0x00, 0x00,
+            0x00, 0x1d, 0x00, 0x00, 0x00, 0x65, 0x6e, 0x63, 0x6f, 0x64, 0x65, 0x72, 0x3d, 0x4c,
+            0x61, 0x76, 0x63, 0x35, 0x38, 0x2e, 0x35, 0x34, 0x2e, 0x31, 0x30, 0x30, 0x20, 0x6c,
+            0x69, 0x62, 0x6f, 0x70, 0x75, 0x73,
+        ]);
+        let opus_tags = OpusTags::read(&mut rdr).unwrap();
+        assert_eq!(opus_tags.vendor_string, "Lavf58.29.100");
+        assert_eq!(
+            opus_tags.user_comments,
+            vec!["encoder=Lavc58.54.100 libopus"]
+        );
+
+        let read_err = rdr.read_exact(&mut [0]).expect_err("should be EOF");
+        assert_eq!(read_err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    pub fn opus_tags_to_caf_info() {
+        let opus_tags = OpusTags {
+            vendor_string: "Lavf58.29.100".to_string(),
+            user_comments: vec![
+                "TITLE=Track One".to_string(),
+                "ARTIST=Some Artist".to_string(),
+                "ENCODER=Lavc58.54.100 libopus".to_string(),
+            ],
+        };
+        assert_eq!(
+            opus_tags.to_caf_info(),
+            vec![
+                ("encoder".to_string(), "Lavf58.29.100".to_string()),
+                ("title".to_string(), "Track One".to_string()),
+                ("artist".to_string(), "Some Artist".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn gain_none_without_output_gain_or_comments() {
+        let opus_head = OpusHead {
+            channel_count: 2,
+            preskip: 0,
+            input_sample_rate: 48000,
+            output_gain: 0,
+            channel_mapping_family: 0,
+            channel_mapping_table: None,
+        };
+        let opus_tags = OpusTags {
+            vendor_string: "test".to_string(),
+            user_comments: vec![],
+        };
+        assert_eq!(effective_gain_db(&opus_head, &opus_tags, GainMode::None), None);
+    }
+
+    #[test]
+    pub fn gain_combines_header_and_track_gain() {
+        let opus_head = OpusHead {
+            channel_count: 2,
+            preskip: 0,
+            input_sample_rate: 48000,
+            output_gain: 256, // +1.0 dB
+            channel_mapping_family: 0,
+            channel_mapping_table: None,
+        };
+        let opus_tags = OpusTags {
+            vendor_string: "test".to_string(),
+            user_comments: vec!["R128_TRACK_GAIN=-512".to_string()], // -2.0 dB
+        };
+        let gain = effective_gain_db(&opus_head, &opus_tags, GainMode::TrackGain).unwrap();
+        assert!((gain - (-1.0)).abs() < f64::EPSILON);
+
+        // album gain mode should ignore the track gain comment
+        assert_eq!(
+            effective_gain_db(&opus_head, &opus_tags, GainMode::AlbumGain),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    pub fn opus_frame_count_code0_celt_20ms() {
+        // config 19 (CELT, 20ms), code 0 (1 frame)
+        let toc = (19 << 3) | 0x0;
+        assert_eq!(packet_frame_count(&[toc]).unwrap(), 960);
+    }
+
+    #[test]
+    pub fn opus_frame_count_code1_silk_60ms() {
+        // config 3 (SILK NB, 60ms), code 1 (2 frames of equal size)
+        let toc = (3 << 3) | 0x1;
+        assert_eq!(packet_frame_count(&[toc, 0x00]).unwrap(), 2 * 60 * 48);
+    }
+
+    #[test]
+    pub fn opus_frame_count_code3_arbitrary() {
+        // config 16 (CELT, 2.5ms), code 3 with a frame count byte of 5
+        let toc = (16 << 3) | 0x3;
+        assert_eq!(packet_frame_count(&[toc, 0x05]).unwrap(), 5 * 120);
+    }
+
+    #[test]
+    pub fn opus_frame_count_empty_packet() {
+        assert!(packet_frame_count(&[]).is_err());
+    }
+
+    #[test]
+    pub fn channel_mapping_table_round_trips_through_info_value() {
+        let table = ChannelMappingTable {
+            stream_count: 4,
+            coupled_count: 2,
+            channel_mapping: vec![0, 1, 2, 3, 4, 5],
+        };
+        let encoded = encode_channel_mapping_table(1, &table);
+        let (family, decoded) = decode_channel_mapping_table(&encoded).unwrap();
+        assert_eq!(family, 1);
+        assert_eq!(decoded.stream_count, 4);
+        assert_eq!(decoded.coupled_count, 2);
+        assert_eq!(decoded.channel_mapping, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    pub fn decode_channel_mapping_table_rejects_malformed_value() {
+        assert!(decode_channel_mapping_table("not a table").is_none());
+        assert!(decode_channel_mapping_table("1,2").is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Error};
+use std::io::Read;
+
+/// Vorbis comment keys that have a well-known CAF Information chunk equivalent. Shared by
+/// the Opus and Vorbis codec paths, which both carry metadata as Vorbis comments.
+/// See the CAF spec's "Information Chunk" section and the Vorbis comment field spec.
+const TAG_KEY_MAP: &[(&str, &str)] = &[
+    ("TITLE", "title"),
+    ("ARTIST", "artist"),
+    ("ALBUM", "album"),
+    ("DATE", "date"),
+    ("GENRE", "genre"),
+];
+
+/// Maps a vendor string and a set of `(key, value)` Vorbis comments to CAF Information
+/// chunk entries, in order. Unrecognized comment keys are dropped.
+pub(crate) fn map_comments_to_caf_info<'a>(
+    vendor: &str,
+    comments: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Vec<(String, String)> {
+    let mut info = vec![("encoder".to_string(), vendor.to_string())];
+
+    for (key, value) in comments {
+        let key = key.to_ascii_uppercase();
+        if let Some((_, caf_key)) = TAG_KEY_MAP.iter().find(|(k, _)| *k == key) {
+            info.push((caf_key.to_string(), value.to_string()));
+        }
+    }
+
+    info
+}
+
+/// Reverses [`map_comments_to_caf_info`]: splits CAF Information chunk entries back into a
+/// vendor string and `KEY=value` Vorbis comments. Unrecognized keys (including `gain`,
+/// which isn't a Vorbis comment) are dropped.
+pub(crate) fn map_caf_info_to_comments(info: &[(String, String)]) -> (String, Vec<String>) {
+    let vendor = info
+        .iter()
+        .find(|(k, _)| k == "encoder")
+        .map(|(_, v)| v.clone())
+        .unwrap_or_default();
+
+    let comments = info
+        .iter()
+        .filter_map(|(caf_key, value)| {
+            TAG_KEY_MAP
+                .iter()
+                .find(|(_, k)| k == caf_key)
+                .map(|(vorbis_key, _)| format!("{vorbis_key}={value}"))
+        })
+        .collect();
+
+    (vendor, comments)
+}
+
+/// Reads a `len`-byte buffer from `rdr` without trusting `len` to be a reasonable size:
+/// the allocation is attempted with `try_reserve` so a hostile length (e.g. a crafted Ogg
+/// page claiming a multi-gigabyte string) fails cleanly instead of aborting the process.
+pub(crate) fn read_length_prefixed_bytes<T: Read>(mut rdr: T, len: u32) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len as usize)
+        .map_err(|_| anyhow!("declared length {len} is too large to allocate"))?;
+
+    rdr.by_ref().take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(anyhow!(
+            "unexpected EOF: expected {len} bytes, got {}",
+            buf.len()
+        ));
+    }
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{map_caf_info_to_comments, map_comments_to_caf_info, read_length_prefixed_bytes};
+    use std::io::Cursor;
+
+    #[test]
+    pub fn maps_known_keys_and_drops_unknown_ones() {
+        let comments = vec![("TITLE", "Track One"), ("X-CUSTOM", "ignored")];
+        assert_eq!(
+            map_comments_to_caf_info("Lavf58.29.100", comments.into_iter()),
+            vec![
+                ("encoder".to_string(), "Lavf58.29.100".to_string()),
+                ("title".to_string(), "Track One".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn maps_caf_info_back_to_comments() {
+        let info = vec![
+            ("encoder".to_string(), "Lavf58.29.100".to_string()),
+            ("title".to_string(), "Track One".to_string()),
+            ("gain".to_string(), "1.500".to_string()),
+        ];
+        let (vendor, comments) = map_caf_info_to_comments(&info);
+        assert_eq!(vendor, "Lavf58.29.100");
+        assert_eq!(comments, vec!["TITLE=Track One".to_string()]);
+    }
+
+    #[test]
+    pub fn rejects_declared_length_past_available_bytes() {
+        let rdr = Cursor::new(&[0x01, 0x02, 0x03]);
+        let err =
+            read_length_prefixed_bytes(rdr, u32::MAX).expect_err("should reject truncated buffer");
+        let message = err.to_string();
+        assert!(message.contains("unexpected EOF") || message.contains("too large"));
+    }
+
+    #[test]
+    pub fn reads_exact_length_prefixed_bytes() {
+        let rdr = Cursor::new(&[0x01, 0x02, 0x03, 0x04]);
+        let bytes = read_length_prefixed_bytes(rdr, 3).unwrap();
+        assert_eq!(bytes, vec![0x01, 0x02, 0x03]);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::util::map_comments_to_caf_info;
+use anyhow::{anyhow, Error};
+use caf::{chunks::AudioDescription, writing::PacketWriter, FormatType};
+use lewton::header::{read_header_comment, read_header_ident};
+use ogg::{Packet, PacketReader};
+use std::io::{Read, Seek, Write};
+
+pub(crate) fn convert<R: Read + Seek, W: Write>(
+    id_header_packet: Packet,
+    mut packet_reader: PacketReader<R>,
+    wtr: W,
+) -> Result<(), Error> {
+    let comment_header_packet = packet_reader
+        .read_packet()?
+        .ok_or(anyhow!("missing comment header packet"))?;
+    // the setup header is part of the three-packet Vorbis header sequence but carries
+    // nothing we need for the CAF audio description or Information chunk
+    let _setup_header_packet = packet_reader
+        .read_packet()?
+        .ok_or(anyhow!("missing setup header packet"))?;
+
+    let ident_header = read_header_ident(&id_header_packet.data)
+        .map_err(|e| anyhow!("invalid vorbis identification header: {e}"))?;
+    let comment_header = read_header_comment(&comment_header_packet.data)
+        .map_err(|e| anyhow!("invalid vorbis comment header: {e}"))?;
+
+    let audio_description =
+        vorbis_audio_description(ident_header.audio_channels, ident_header.audio_sample_rate);
+
+    let mut packet_writer = PacketWriter::new(wtr, &audio_description)?;
+
+    // carry the Vorbis comments from the comment header into a CAF Information chunk
+    let comments = comment_header
+        .comment_list
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+    let info = map_comments_to_caf_info(&comment_header.vendor, comments);
+    packet_writer.write_info_chunk(&info)?;
+
+    // read audio data packets from ogg and add them to caf
+    //
+    // unlike Opus, a Vorbis packet's sample count depends on which of the two window
+    // sizes the encoder chose for it, which isn't recoverable without decoding the
+    // packet against the setup header's mode list. We pass `None` here and rely on the
+    // CAF packet table's own byte-length bookkeeping rather than per-packet frame counts.
+    while let Some(packet) = packet_reader.read_packet()? {
+        packet_writer.add_packet(&packet.data, None)?;
+    }
+    packet_writer.write_audio_data()?;
+
+    Ok(())
+}
+
+/// Builds the CAF audio description for a Vorbis stream with the given channel count and
+/// sample rate, split out from [`convert`] so it can be exercised without a real Ogg/lewton
+/// header to parse.
+fn vorbis_audio_description(channels: u8, sample_rate: u32) -> AudioDescription {
+    AudioDescription {
+        sample_rate: sample_rate as f64,
+        format_id: FormatType::Other(u32::from_be_bytes(*b"vorb")),
+        format_flags: 0,
+        bytes_per_packet: 0,
+        frames_per_packet: 0, // vorbis packets are variable-length, so this is a VBR stream
+        channels_per_frame: channels as u32,
+        bits_per_channel: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::vorbis_audio_description;
+    use caf::FormatType;
+
+    #[test]
+    pub fn audio_description_carries_channels_and_sample_rate() {
+        let audio_description = vorbis_audio_description(2, 44100);
+        assert_eq!(audio_description.channels_per_frame, 2);
+        assert_eq!(audio_description.sample_rate, 44100.0);
+        assert_eq!(
+            audio_description.format_id,
+            FormatType::Other(u32::from_be_bytes(*b"vorb"))
+        );
+    }
+
+    #[test]
+    pub fn audio_description_is_variable_bit_rate() {
+        let audio_description = vorbis_audio_description(1, 48000);
+        assert_eq!(audio_description.frames_per_packet, 0);
+        assert_eq!(audio_description.bytes_per_packet, 0);
+    }
+}